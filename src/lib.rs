@@ -1,18 +1,59 @@
-use std::cmp::Ordering;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
 use std::fmt;
 use std::fs::File;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Seek, SeekFrom, Write};
 use std::num::{ParseFloatError, ParseIntError};
 
-pub struct BedFile {
+pub struct BedFile<R> {
     pub lineno: usize,
     pub last: Option<String>,
     pub filename: String,
-    file: io::BufReader<File>,
+    file: R,
     bufsize: usize, // hint for how big the buffer should be
     at_eof: bool,
+    schema: BedSchema,
 }
 
+/// Which whitespace-separated column of a BED-like file holds each field
+/// `bedpool` needs. Lets non-dmap2 layouts (e.g. Bismark coverage files,
+/// where the counts come before the context columns) be pooled without
+/// pre-processing. `chrom` is always column 0; `ratio` is never read from a
+/// column, it is always derived as `meth / cov`.
+#[derive(Clone, Copy, Debug)]
+pub struct BedSchema {
+    pub start_col: usize,
+    pub end_col: usize,
+    pub meth_col: usize,
+    pub cov_col: usize,
+    /// Keep whitespace-separated columns past the ones named above and
+    /// re-emit them verbatim instead of dropping them.
+    pub keep_extra: bool,
+}
+
+impl Default for BedSchema {
+    fn default() -> Self {
+        BedSchema {
+            start_col: 1,
+            end_col: 2,
+            meth_col: 4,
+            cov_col: 5,
+            keep_extra: true,
+        }
+    }
+}
+
+impl BedSchema {
+    fn min_columns(&self) -> usize {
+        self.start_col
+            .max(self.end_col)
+            .max(self.meth_col)
+            .max(self.cov_col)
+            + 1
+    }
+}
+
+#[derive(Debug)]
 pub enum BedError {
     IO(io::Error),
     File(String, io::Error),
@@ -36,47 +77,48 @@ impl fmt::Display for BedError {
 }
 
 trait ToBedErr {
-    fn bed_error(self, bf: &BedFile) -> BedError;
+    fn bed_error(self, filename: &str, lineno: usize) -> BedError;
 }
 
 impl ToBedErr for io::Error {
-    fn bed_error(self, bf: &BedFile) -> BedError {
-        BedError::File(bf.filename.clone(), self)
+    fn bed_error(self, filename: &str, _lineno: usize) -> BedError {
+        BedError::File(filename.to_string(), self)
     }
 }
 
 impl ToBedErr for ParseIntError {
-    fn bed_error(self, bf: &BedFile) -> BedError {
+    fn bed_error(self, filename: &str, lineno: usize) -> BedError {
         BedError::Parse(
-            bf.filename.clone(),
-            bf.lineno,
+            filename.to_string(),
+            lineno,
             format!("expected integer, but {}", self),
         )
     }
 }
 
 impl ToBedErr for ParseFloatError {
-    fn bed_error(self, bf: &BedFile) -> BedError {
+    fn bed_error(self, filename: &str, lineno: usize) -> BedError {
         BedError::Parse(
-            bf.filename.clone(),
-            bf.lineno,
+            filename.to_string(),
+            lineno,
             format!("expected float, but {}", self),
         )
     }
 }
 
 trait ToBedResult<T> {
-    fn bed_result(self: Self, bf: &BedFile) -> Result<T, BedError>;
+    fn bed_result(self, filename: &str, lineno: usize) -> Result<T, BedError>;
 }
 
 impl<T, E: ToBedErr> ToBedResult<T> for Result<T, E> {
-    fn bed_result(self: Self, bf: &BedFile) -> Result<T, BedError> {
-        self.map_err(|e| e.bed_error(bf))
+    fn bed_result(self, filename: &str, lineno: usize) -> Result<T, BedError> {
+        self.map_err(|e| e.bed_error(filename, lineno))
     }
 }
 
-impl BedFile {
-    pub fn new(fname: &str) -> Result<Self, BedError> {
+impl BedFile<io::BufReader<File>> {
+    /// Open a BED file from disk, buffering reads from it.
+    pub fn open(fname: &str) -> Result<Self, BedError> {
         let filename = fname.to_string();
         let file = match File::open(fname) {
             Err(io_error) => {
@@ -84,96 +126,224 @@ impl BedFile {
             }
             Ok(f) => io::BufReader::new(f),
         };
-        Ok(BedFile {
+        Ok(Self::from_reader(filename, file))
+    }
+}
+
+impl<R: BufRead> BedFile<R> {
+    /// Wrap an already-buffered reader (stdin, a pipe, a decompressing
+    /// reader, ...) as a `BedFile`.
+    pub fn from_reader(filename: impl Into<String>, file: R) -> Self {
+        BedFile {
             lineno: 0,
             last: None,
-            filename,
+            filename: filename.into(),
             file,
             bufsize: 32,
             at_eof: false,
+            schema: BedSchema::default(),
+        }
+    }
+
+    /// Parse columns by `schema` instead of the default dmap2 layout.
+    pub fn with_schema(mut self, schema: BedSchema) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    /// Parse a single BED line into a record, given the file/line it came from
+    /// (for error messages) and the column layout to use. Shared by
+    /// `Iterator::next()` and `query()`, which parses candidate lines found by
+    /// binary search without advancing `self`.
+    fn parse_line(
+        filename: &str,
+        lineno: usize,
+        line: &str,
+        schema: &BedSchema,
+    ) -> Result<BedRecord, BedError> {
+        let parts: Vec<&str> = line.split_ascii_whitespace().collect();
+        let needed = schema.min_columns();
+        if parts.len() < needed {
+            return Err(BedError::Parse(
+                filename.to_string(),
+                lineno,
+                format!("expected at least {} columns, got {}", needed, parts.len()),
+            ));
+        }
+        let chrom = parts[0].to_string();
+        let start = parts[schema.start_col].parse().bed_result(filename, lineno)?;
+        let end = parts[schema.end_col].parse().bed_result(filename, lineno)?;
+        let meth: f32 = parts[schema.meth_col].parse().bed_result(filename, lineno)?;
+        let cov: f32 = parts[schema.cov_col].parse().bed_result(filename, lineno)?;
+        let extra = if schema.keep_extra && parts.len() > needed {
+            Some(parts[needed..].join("\t"))
+        } else {
+            None
+        };
+        Ok(BedRecord {
+            coords: BedCoords { chrom, start, end },
+            ratio: meth / cov,
+            meth,
+            cov,
+            extra,
         })
     }
+}
+
+impl<R: BufRead> Iterator for BedFile<R> {
+    type Item = Result<BedRecord, BedError>;
 
-    pub fn next(&mut self) -> Result<Option<BedRecord>, BedError> {
+    fn next(&mut self) -> Option<Self::Item> {
         if self.at_eof {
-            return Ok(None);
+            return None;
         }
         let mut buffer = String::with_capacity(self.bufsize);
-        self.bufsize = self.file.read_line(&mut buffer).bed_result(self)?;
+        match self
+            .file
+            .read_line(&mut buffer)
+            .bed_result(&self.filename, self.lineno)
+        {
+            Ok(n) => self.bufsize = n,
+            Err(e) => return Some(Err(e)),
+        }
         if self.bufsize == 0 {
             self.at_eof = true;
-            return Ok(None);
+            return None;
         }
         self.lineno += 1;
+        let rec = match Self::parse_line(&self.filename, self.lineno, &buffer, &self.schema) {
+            Ok(rec) => rec,
+            Err(e) => return Some(Err(e)),
+        };
         self.last = Some(buffer);
+        Some(Ok(rec))
+    }
+}
 
-        // annotate the BedRecord
-        if let Some(ref line) = self.last {
-            let parts: Vec<&str> = line.split_ascii_whitespace().take(6).collect();
-            if parts.len() < 6 {
-                return Err(BedError::Parse(
-                    self.filename.clone(),
-                    self.lineno,
-                    format!("expected at least 6 columns, got {}", parts.len()),
-                ));
+impl<R: BufRead + Seek> BedFile<R> {
+    /// Return all records on `chrom` whose start lies in `[start, end)`, without
+    /// scanning the whole file, by binary-searching for the first line at or
+    /// after the target coordinates and then reading forward. A record that
+    /// begins before `start` is not returned, even if it extends into the range.
+    ///
+    /// This exploits the fact that BED input is coordinate-sorted: we treat the
+    /// underlying file as an array of lines addressed by byte offset, seek to
+    /// the midpoint of the current search range, and snap forward to the next
+    /// line boundary before comparing. Offset 0 is handled specially since a
+    /// probe landing inside the first line would otherwise skip over it; a
+    /// probe that already lands on a line boundary (the byte before it is a
+    /// `\n`) is left alone rather than snapped past the line it points at,
+    /// since that line is still a valid candidate.
+    pub fn query(&mut self, chrom: &str, start: u64, end: u64) -> Result<Vec<BedRecord>, BedError> {
+        let file_len = self
+            .file
+            .seek(SeekFrom::End(0))
+            .bed_result(&self.filename, self.lineno)?;
+
+        let mut lo = 0u64;
+        let mut hi = file_len;
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let line_start = if mid == 0 {
+                0
+            } else {
+                self.file
+                    .seek(SeekFrom::Start(mid - 1))
+                    .bed_result(&self.filename, self.lineno)?;
+                let mut prev_byte = [0u8; 1];
+                self.file
+                    .read_exact(&mut prev_byte)
+                    .bed_result(&self.filename, self.lineno)?;
+                if prev_byte[0] == b'\n' {
+                    mid
+                } else {
+                    let mut discarded = Vec::new();
+                    let skipped = self
+                        .file
+                        .read_until(b'\n', &mut discarded)
+                        .bed_result(&self.filename, self.lineno)? as u64;
+                    mid + skipped
+                }
+            };
+            if line_start >= file_len {
+                hi = mid;
+                continue;
+            }
+
+            self.file
+                .seek(SeekFrom::Start(line_start))
+                .bed_result(&self.filename, self.lineno)?;
+            let mut line = String::new();
+            self.file
+                .read_line(&mut line)
+                .bed_result(&self.filename, self.lineno)?;
+            let coords = Self::parse_line(&self.filename, self.lineno, &line, &self.schema)?.coords;
+
+            if (coords.chrom.as_str(), coords.start) < (chrom, start) {
+                lo = line_start + line.len() as u64;
+            } else {
+                hi = mid;
             }
-            let chrom = parts[0];
-            let start = parts[1].parse().bed_result(self)?;
-            let end = parts[2].parse().bed_result(self)?;
-            let ratio = parts[3].parse().bed_result(self)?;
-            let meth = parts[4].parse().bed_result(self)?;
-            let cov = parts[5].parse().bed_result(self)?;
-            Ok(Some(BedRecord {
-                coords: BedCoords { chrom, start, end },
-                ratio,
-                meth,
-                cov,
-            }))
-        } else {
-            unreachable!()
         }
+
+        self.file
+            .seek(SeekFrom::Start(lo))
+            .bed_result(&self.filename, self.lineno)?;
+        self.at_eof = false;
+
+        let mut hits = Vec::new();
+        while let Some(rec) = self.next().transpose()? {
+            if rec.coords.chrom != chrom || rec.coords.start >= end {
+                break;
+            }
+            hits.push(rec);
+        }
+        Ok(hits)
     }
 }
 
-pub fn sync2(mut file1: BedFile, mut file2: BedFile) -> Result<(), BedError> {
-    // assume the files are unitialized
-    let mut maybe_rec1 = file1.next()?;
-    let mut maybe_rec2 = file2.next()?;
+pub fn sync2<R1: BufRead, R2: BufRead>(
+    file1: BedFile<R1>,
+    file2: BedFile<R2>,
+) -> Result<(), BedError> {
+    sync2_to(file1, file2, &mut io::stdout())
+}
+
+/// Body of `sync2`, parameterized over the output sink so the merge logic
+/// can be exercised in tests without capturing stdout.
+fn sync2_to<W: Write, R1: BufRead, R2: BufRead>(
+    file1: BedFile<R1>,
+    file2: BedFile<R2>,
+    out: &mut W,
+) -> Result<(), BedError> {
+    let mut iter1 = file1.peekable();
+    let mut iter2 = file2.peekable();
+
     loop {
-        match (maybe_rec1.as_ref(), maybe_rec2.as_ref()) {
-            (Some(rec1), Some(rec2)) => match rec1.coords.cmp(&rec2.coords) {
-                Ordering::Equal => {
-                    let meth = rec1.meth + rec2.meth;
-                    let cov = rec1.cov + rec2.cov;
-                    let ratio = meth / cov;
-                    println!(
-                        "{}",
-                        BedRecord {
-                            ratio,
-                            meth,
-                            cov,
-                            ..maybe_rec1.unwrap()
-                        }
-                    );
-                    maybe_rec1 = file1.next()?;
-                    maybe_rec2 = file2.next()?;
-                }
-                Ordering::Less => {
-                    println!("{}", rec1);
-                    maybe_rec1 = file1.next()?;
-                }
-                Ordering::Greater => {
-                    println!("{}", rec2);
-                    maybe_rec2 = file2.next()?;
-                }
-            },
-            (Some(rec), None) | (None, Some(rec)) => {
-                println!("{}", rec);
-                maybe_rec1 = file1.next()?;
-                maybe_rec2 = file2.next()?;
+        let ord = match (iter1.peek(), iter2.peek()) {
+            (Some(Ok(rec1)), Some(Ok(rec2))) => rec1.coords.cmp(&rec2.coords),
+            (Some(Err(_)), _) => return Err(iter1.next().unwrap().unwrap_err()),
+            (_, Some(Err(_))) => return Err(iter2.next().unwrap().unwrap_err()),
+            (Some(Ok(_)), None) => Ordering::Less,
+            (None, Some(Ok(_))) => Ordering::Greater,
+            (None, None) => break,
+        };
+
+        match ord {
+            Ordering::Equal => {
+                let rec1 = iter1.next().unwrap()?;
+                let rec2 = iter2.next().unwrap()?;
+                let meth = rec1.meth + rec2.meth;
+                let cov = rec1.cov + rec2.cov;
+                let ratio = meth / cov;
+                writeln!(out, "{}", BedRecord { ratio, meth, cov, ..rec1 })
+                    .expect("failed to write to output");
             }
-            (None, None) => {
-                break;
+            Ordering::Less => {
+                writeln!(out, "{}", iter1.next().unwrap()?).expect("failed to write to output")
+            }
+            Ordering::Greater => {
+                writeln!(out, "{}", iter2.next().unwrap()?).expect("failed to write to output")
             }
         }
     }
@@ -181,26 +351,382 @@ pub fn sync2(mut file1: BedFile, mut file2: BedFile) -> Result<(), BedError> {
 }
 
 #[derive(Debug)]
-pub struct BedRecord<'a> {
-    coords: BedCoords<'a>,
+pub struct BedRecord {
+    coords: BedCoords,
     ratio: f32,
     meth: f32,
     cov: f32,
+    /// Columns past the ones named in `BedSchema`, tab-joined verbatim. `None`
+    /// when the schema dropped them or the source line had none.
+    extra: Option<String>,
 }
 
-impl<'a> fmt::Display for BedRecord<'a> {
+impl fmt::Display for BedRecord {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
             "{}\t{}\t{}\t{}\t{}\t{}",
             self.coords.chrom, self.coords.start, self.coords.end, self.ratio, self.meth, self.cov,
-        )
+        )?;
+        if let Some(extra) = &self.extra {
+            write!(f, "\t{}", extra)?;
+        }
+        Ok(())
     }
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
-struct BedCoords<'a> {
-    chrom: &'a str,
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct BedCoords {
+    chrom: String,
     start: u64,
     end: u64,
 }
+
+/// k-way merge of any number of `BedFile`s, pooling records that share the same
+/// `BedCoords` across files the same way `sync2` pools a pair.
+///
+/// Each file contributes at most one candidate record to a `BinaryHeap` at a
+/// time, keyed on `(BedCoords, file_index)` and wrapped in `Reverse` so the
+/// heap pops the smallest coordinate first. Every entry sharing the current
+/// minimum is popped, its `meth`/`cov` summed, and a replacement record is
+/// pulled from each of those files and pushed back onto the heap.
+pub fn syncn<R: BufRead>(files: Vec<BedFile<R>>) -> Result<(), BedError> {
+    syncn_to(files, &mut io::stdout())
+}
+
+/// Body of `syncn`, parameterized over the output sink so the merge logic
+/// can be exercised in tests without capturing stdout.
+fn syncn_to<W: Write, R: BufRead>(mut files: Vec<BedFile<R>>, out: &mut W) -> Result<(), BedError> {
+    let mut heap: BinaryHeap<Reverse<(BedCoords, usize)>> = BinaryHeap::new();
+    let mut current: Vec<Option<BedRecord>> = Vec::with_capacity(files.len());
+
+    for (i, file) in files.iter_mut().enumerate() {
+        let rec = file.next().transpose()?;
+        if let Some(ref rec) = rec {
+            heap.push(Reverse((rec.coords.clone(), i)));
+        }
+        current.push(rec);
+    }
+
+    while let Some(&Reverse((ref min_coords, _))) = heap.peek() {
+        let min_coords = min_coords.clone();
+        let mut meth = 0.0;
+        let mut cov = 0.0;
+        let mut popped = Vec::new();
+        // Heap ties break on ascending file index, so the first record popped
+        // for this coordinate is from the lowest-indexed file; its `extra`
+        // columns are the ones carried through to the merged record.
+        let mut extra = None;
+
+        while let Some(&Reverse((ref coords, _))) = heap.peek() {
+            if *coords != min_coords {
+                break;
+            }
+            let Reverse((_, idx)) = heap.pop().unwrap();
+            let rec = current[idx]
+                .take()
+                .expect("file index on the heap always has a pending record");
+            meth += rec.meth;
+            cov += rec.cov;
+            if extra.is_none() {
+                extra = rec.extra;
+            }
+            popped.push(idx);
+        }
+
+        writeln!(
+            out,
+            "{}",
+            BedRecord {
+                coords: min_coords,
+                ratio: meth / cov,
+                meth,
+                cov,
+                extra,
+            }
+        )
+        .expect("failed to write to output");
+
+        for idx in popped {
+            if let Some(rec) = files[idx].next().transpose()? {
+                heap.push(Reverse((rec.coords.clone(), idx)));
+                current[idx] = Some(rec);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Raise the process's open file descriptor limit toward its hard limit.
+///
+/// Pooling dozens of per-sample BED files at once can exceed the default
+/// `RLIMIT_NOFILE` on macOS/Linux, so `main` calls this before opening the
+/// file list. This is a no-op on platforms without POSIX rlimits.
+#[cfg(unix)]
+pub fn raise_fd_limit() -> io::Result<()> {
+    unsafe {
+        let mut limits = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if libc::getrlimit(libc::RLIMIT_NOFILE, &mut limits) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        limits.rlim_cur = limits.rlim_max;
+        if libc::setrlimit(libc::RLIMIT_NOFILE, &limits) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn raise_fd_limit() -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn cursor_file(contents: &str) -> BedFile<Cursor<Vec<u8>>> {
+        BedFile::from_reader("test", Cursor::new(contents.as_bytes().to_vec()))
+    }
+
+    /// `n` dmap2-style records on two chroms, all fields zero-padded to the
+    /// same width so that some binary-search midpoints land exactly on line
+    /// boundaries while others land inside a line.
+    fn uniform_records(n: usize) -> String {
+        let mut out = String::new();
+        for i in 0..n {
+            let chrom = if i < n / 2 { "chr1" } else { "chr2" };
+            let start = (i % (n / 2)) * 10;
+            out.push_str(&format!(
+                "{}\t{:04}\t{:04}\t.\t05\t10\n",
+                chrom,
+                start,
+                start + 10
+            ));
+        }
+        out
+    }
+
+    /// Brute-force oracle: every record on `chrom` whose start falls in
+    /// `[start, end)`, in file order.
+    fn linear_query(contents: &str, chrom: &str, start: u64, end: u64) -> Vec<(u64, u64)> {
+        contents
+            .lines()
+            .filter_map(|line| {
+                let parts: Vec<&str> = line.split_ascii_whitespace().collect();
+                if parts[0] != chrom {
+                    return None;
+                }
+                let s: u64 = parts[1].parse().unwrap();
+                let e: u64 = parts[2].parse().unwrap();
+                if s >= start && s < end {
+                    Some((s, e))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn query_matches_linear_scan_over_all_offsets() {
+        let contents = uniform_records(10);
+        for start in 0..70 {
+            for end in (start + 1)..80 {
+                let mut file = cursor_file(&contents);
+                let got: Vec<(u64, u64)> = file
+                    .query("chr1", start, end)
+                    .unwrap()
+                    .iter()
+                    .map(|r| (r.coords.start, r.coords.end))
+                    .collect();
+                let want = linear_query(&contents, "chr1", start, end);
+                assert_eq!(got, want, "query(chr1, {}, {})", start, end);
+            }
+        }
+    }
+
+    #[test]
+    fn query_excludes_record_strictly_before_start() {
+        // chr1 has records at starts 0, 10, 20, 30, 40; [41, 42) must not
+        // pick up the 40-50 record even when its probe lands on a line start.
+        let contents = uniform_records(10);
+        let mut file = cursor_file(&contents);
+        let got = file.query("chr1", 41, 42).unwrap();
+        assert!(got.is_empty(), "got {:?}", got);
+    }
+
+    #[test]
+    fn query_before_first_record_is_empty() {
+        let contents = uniform_records(10);
+        let mut file = cursor_file(&contents);
+        assert!(file.query("chr1", 0, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn query_after_last_record_is_empty() {
+        let contents = uniform_records(10);
+        let mut file = cursor_file(&contents);
+        assert!(file.query("chr2", 1_000, 2_000).unwrap().is_empty());
+    }
+
+    #[test]
+    fn query_unknown_chrom_is_empty() {
+        let contents = uniform_records(10);
+        let mut file = cursor_file(&contents);
+        assert!(file.query("chr3", 0, 1_000).unwrap().is_empty());
+    }
+
+    #[test]
+    fn query_respects_chrom_boundary() {
+        let contents = uniform_records(10);
+        let mut file = cursor_file(&contents);
+        let got = file.query("chr1", 0, 1_000).unwrap();
+        assert!(got.iter().all(|r| r.coords.chrom == "chr1"));
+        assert_eq!(got.len(), 5);
+    }
+
+    fn sync2_output<R1: BufRead, R2: BufRead>(file1: BedFile<R1>, file2: BedFile<R2>) -> Vec<String> {
+        let mut out = Vec::new();
+        sync2_to(file1, file2, &mut out).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| l.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn sync2_sums_meth_and_cov_on_matching_coords() {
+        let a = cursor_file("chr1\t0010\t0020\t.\t05\t10\n");
+        let b = cursor_file("chr1\t0010\t0020\t.\t03\t10\n");
+        let lines = sync2_output(a, b);
+        assert_eq!(lines, vec!["chr1\t10\t20\t0.4\t8\t20".to_string()]);
+    }
+
+    #[test]
+    fn sync2_passes_through_non_overlapping_records_in_order() {
+        let a = cursor_file("chr1\t0010\t0020\t.\t05\t10\nchr1\t0040\t0050\t.\t05\t10\n");
+        let b = cursor_file("chr1\t0020\t0030\t.\t05\t10\n");
+        let lines = sync2_output(a, b);
+        assert_eq!(
+            lines,
+            vec![
+                "chr1\t10\t20\t0.5\t5\t10".to_string(),
+                "chr1\t20\t30\t0.5\t5\t10".to_string(),
+                "chr1\t40\t50\t0.5\t5\t10".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn sync2_drains_the_longer_file_after_the_shorter_one_is_exhausted() {
+        let a = cursor_file("chr1\t0010\t0020\t.\t05\t10\n");
+        let b = cursor_file("chr1\t0010\t0020\t.\t05\t10\nchr2\t0000\t0010\t.\t05\t10\n");
+        let lines = sync2_output(a, b);
+        assert_eq!(
+            lines,
+            vec![
+                "chr1\t10\t20\t0.5\t10\t20".to_string(),
+                "chr2\t0\t10\t0.5\t5\t10".to_string(),
+            ]
+        );
+    }
+
+    fn syncn_output<R: BufRead>(files: Vec<BedFile<R>>) -> Vec<String> {
+        let mut out = Vec::new();
+        syncn_to(files, &mut out).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| l.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn syncn_sums_meth_and_cov_on_matching_coords() {
+        let a = cursor_file("chr1\t0010\t0020\t.\t05\t10\n");
+        let b = cursor_file("chr1\t0010\t0020\t.\t03\t10\n");
+        let lines = syncn_output(vec![a, b]);
+        assert_eq!(lines, vec!["chr1\t10\t20\t0.4\t8\t20".to_string()]);
+    }
+
+    #[test]
+    fn syncn_keeps_extra_from_lowest_indexed_file_on_tie() {
+        let a = cursor_file("chr1\t0010\t0020\t.\t05\t10\tfoo\n");
+        let b = cursor_file("chr1\t0010\t0020\t.\t03\t10\tbar\n");
+        let lines = syncn_output(vec![a, b]);
+        assert_eq!(lines, vec!["chr1\t10\t20\t0.4\t8\t20\tfoo".to_string()]);
+    }
+
+    #[test]
+    fn syncn_passes_through_non_overlapping_records_untouched() {
+        let a = cursor_file("chr1\t0010\t0020\t.\t05\t10\n");
+        let b = cursor_file("chr1\t0030\t0040\t.\t05\t10\n");
+        let lines = syncn_output(vec![a, b]);
+        assert_eq!(
+            lines,
+            vec![
+                "chr1\t10\t20\t0.5\t5\t10".to_string(),
+                "chr1\t30\t40\t0.5\t5\t10".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn syncn_merges_three_way_tie_in_file_order() {
+        let a = cursor_file("chr1\t0010\t0020\t.\t01\t10\ta\n");
+        let b = cursor_file("chr1\t0010\t0020\t.\t01\t10\tb\n");
+        let c = cursor_file("chr1\t0010\t0020\t.\t01\t10\tc\n");
+        let lines = syncn_output(vec![a, b, c]);
+        assert_eq!(lines, vec!["chr1\t10\t20\t0.1\t3\t30\ta".to_string()]);
+    }
+
+    #[test]
+    fn parse_line_honors_custom_schema_and_keeps_extra_columns() {
+        // A layout with meth/cov immediately after start/end and no
+        // skipped ratio column, unlike the default dmap2 schema (which
+        // puts meth/cov at columns 4/5 with column 3 unused).
+        let schema = BedSchema {
+            start_col: 1,
+            end_col: 2,
+            meth_col: 3,
+            cov_col: 4,
+            keep_extra: true,
+        };
+        let rec = BedFile::<Cursor<Vec<u8>>>::parse_line(
+            "test",
+            1,
+            "chr1\t100\t200\t4\t8\tcontext\tmore\n",
+            &schema,
+        )
+        .unwrap();
+        assert_eq!(rec.coords.start, 100);
+        assert_eq!(rec.coords.end, 200);
+        assert_eq!(rec.meth, 4.0);
+        assert_eq!(rec.cov, 8.0);
+        assert_eq!(rec.extra.as_deref(), Some("context\tmore"));
+    }
+
+    #[test]
+    fn parse_line_drops_extra_columns_when_schema_says_so() {
+        let schema = BedSchema {
+            keep_extra: false,
+            ..BedSchema::default()
+        };
+        let rec = BedFile::<Cursor<Vec<u8>>>::parse_line(
+            "test",
+            1,
+            "chr1\t100\t200\t.\t4\t8\tcontext\n",
+            &schema,
+        )
+        .unwrap();
+        assert_eq!(rec.extra, None);
+    }
+}
@@ -1,38 +1,145 @@
-use bedpool::{sync2, BedFile};
+use bedpool::{raise_fd_limit, sync2, syncn, BedError, BedFile, BedSchema};
 
 extern crate clap;
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches};
 
-fn show_error<T, E: std::fmt::Display>(foo: Result<T, E>) -> T {
-    foo.unwrap_or_else(|e| {
+use std::fs::File;
+use std::io::{self, BufRead};
+
+#[cfg(feature = "gzip")]
+use flate2::read::MultiGzDecoder;
+
+fn show_error<T, E: std::fmt::Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|e| {
         eprintln!("{}", e);
         std::process::exit(1);
     })
 }
 
+/// Open one of the CLI's positional file arguments as a `BedFile`.
+///
+/// `-` means stdin. For stdin and files alike, the first two bytes are peeked
+/// (without consuming them) to auto-detect a gzip member, so `zcat a.bed.gz |
+/// bedpool - b.bed` works, and with the `gzip` feature so does `bedpool
+/// a.bed.gz b.bed`, without the caller having to say which files are
+/// compressed. Without that feature, gzip input is rejected with an error.
+fn open_input(fname: &str) -> Result<BedFile<Box<dyn BufRead>>, BedError> {
+    let filename = fname.to_string();
+    let mut raw: Box<dyn BufRead> = if fname == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        let file = File::open(fname).map_err(|e| BedError::File(filename.clone(), e))?;
+        Box::new(io::BufReader::new(file))
+    };
+
+    let is_gzip = raw
+        .fill_buf()
+        .map(|peeked| peeked.len() >= 2 && peeked[0] == 0x1f && peeked[1] == 0x8b)
+        .map_err(|e| BedError::File(filename.clone(), e))?;
+
+    if !is_gzip {
+        return Ok(BedFile::from_reader(filename, raw));
+    }
+
+    #[cfg(feature = "gzip")]
+    {
+        let gz = io::BufReader::new(MultiGzDecoder::new(raw));
+        Ok(BedFile::from_reader(filename, Box::new(gz) as Box<dyn BufRead>))
+    }
+    #[cfg(not(feature = "gzip"))]
+    {
+        Err(BedError::File(
+            filename,
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "input looks gzip-compressed, but bedpool was built without the `gzip` feature",
+            ),
+        ))
+    }
+}
+
+/// Parse the `--start-col`/`--end-col`/`--meth-col`/`--cov-col`/`--drop-extra`
+/// flags into a `BedSchema`, falling back to the dmap2 column layout for any
+/// flag the caller didn't pass.
+fn build_schema(matches: &ArgMatches) -> BedSchema {
+    let defaults = BedSchema::default();
+    let col = |name: &str, default: usize| match matches.value_of(name) {
+        None => default,
+        Some(v) => show_error(
+            v.parse::<usize>()
+                .map_err(|e| format!("--{} must be a column index: {}", name, e)),
+        ),
+    };
+    BedSchema {
+        start_col: col("start-col", defaults.start_col),
+        end_col: col("end-col", defaults.end_col),
+        meth_col: col("meth-col", defaults.meth_col),
+        cov_col: col("cov-col", defaults.cov_col),
+        keep_extra: !matches.is_present("drop-extra"),
+    }
+}
+
 fn main() {
     let matches = App::new("bedpool")
         .version("0.1")
         .author("William Owens <wowens@ufl.edu>")
-        .about("Pool 2 BED files from dmap2 together.")
+        .about("Pool 2 or more BED files from dmap2 together.")
         .arg(
-            Arg::with_name("file1")
-                .index(1)
-                .help("First file to pool")
+            Arg::with_name("files")
+                .help("BED files to pool (2 or more); use '-' for stdin")
+                .multiple(true)
+                .min_values(2)
                 .required(true),
         )
         .arg(
-            Arg::with_name("file2")
-                .index(2)
-                .help("Second file to pool")
-                .required(true),
+            Arg::with_name("start-col")
+                .long("start-col")
+                .help("0-based column holding the start coordinate [default: 1]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("end-col")
+                .long("end-col")
+                .help("0-based column holding the end coordinate [default: 2]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("meth-col")
+                .long("meth-col")
+                .help("0-based column holding the methylated-read count [default: 4]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cov-col")
+                .long("cov-col")
+                .help("0-based column holding the total coverage [default: 5]")
+                .takes_value(true),
         )
+        .arg(Arg::with_name("drop-extra").long("drop-extra").help(
+            "Drop columns past start/end/meth/cov instead of passing them through unchanged",
+        ))
         .get_matches();
 
-    let file1 = matches.value_of("file1").unwrap();
-    let file1 = show_error(BedFile::new(file1));
-    let file2 = matches.value_of("file2").unwrap();
-    let file2 = show_error(BedFile::new(file2));
+    // opening many files at once can exhaust the default descriptor limit
+    if let Err(e) = raise_fd_limit() {
+        eprintln!("warning: failed to raise file descriptor limit: {}", e);
+    }
+
+    let schema = build_schema(&matches);
+    let files: Vec<BedFile<Box<dyn BufRead>>> = matches
+        .values_of("files")
+        .unwrap()
+        .map(|fname| show_error(open_input(fname)).with_schema(schema))
+        .collect();
 
-    show_error(sync2(file1, file2));
+    let mut files = files.into_iter();
+    match (files.next(), files.next(), files.next()) {
+        (Some(file1), Some(file2), None) => show_error(sync2(file1, file2)),
+        (Some(file1), Some(file2), Some(file3)) => {
+            let mut all = vec![file1, file2, file3];
+            all.extend(files);
+            show_error(syncn(all));
+        }
+        _ => unreachable!("clap enforces at least 2 files"),
+    }
 }